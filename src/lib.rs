@@ -25,7 +25,7 @@ use pyo3::exceptions::PyException;
 use pyo3::types::{PyDict, PyModule, PyModuleMethods};
 use pyo3::{pyfunction, pymodule, wrap_pyfunction, Bound, PyResult, Python};
 
-use nbt::{McrFileReader, NbtFileReader};
+use nbt::{unpack_section_blocks, McrFileReader, NbtFileReader, RepairReport};
 use texture::transform_image_side;
 
 pyo3::create_exception!(overviewer_core_new, CorruptionError, PyException);
@@ -70,8 +70,10 @@ fn overviewer_core_new(m: &Bound<'_, PyModule>) -> PyResult<()> {
 
     m.add_function(wrap_pyfunction!(load, m)?)?;
     m.add_class::<McrFileReader>()?;
+    m.add_class::<RepairReport>()?;
 
     m.add_function(wrap_pyfunction!(transform_image_side, m)?)?;
+    m.add_function(wrap_pyfunction!(unpack_section_blocks, m)?)?;
 
     Ok(())
 }
@@ -13,13 +13,21 @@
 //    You should have received a copy of the GNU General Public License along
 //    with the Overviewer.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::fs::File;
-use std::io::{BufReader, Cursor, Read};
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 use flate2::bufread::{GzDecoder, ZlibDecoder};
-use pyo3::types::{PyBytes, PyDict, PyDictMethods, PyTuple};
-use pyo3::{pyclass, pymethods, Bound, Py, PyAny, Python, ToPyObject};
+use lz4_flex::frame::FrameDecoder;
+use pyo3::types::{PyAnyMethods, PyBytes, PyDict, PyDictMethods, PyTuple};
+use pyo3::{pyclass, pyfunction, pymethods, Bound, Py, PyAny, PyResult, Python, ToPyObject};
+
+use crate::{CorruptChunkError, CorruptNBTError, CorruptRegionError};
+
+/// Number of bytes in a region file sector.
+const SECTOR_BYTES: usize = 4096;
+/// The `locations`/`timestamps` header occupies the first two sectors.
+const HEADER_SECTORS: u32 = 2;
 
 // @_file_loader
 // def load_region(fileobj):
@@ -75,7 +83,7 @@ impl<R: Read> NbtFileReader<R> {
         }
     }
 
-    fn read(&mut self, len: usize) -> &[u8] {
+    fn read(&mut self, len: usize) -> PyResult<&[u8]> {
         if self.buf.len() < len {
             let remaining = len - self.buf.len();
             self.buf.reserve(remaining);
@@ -86,147 +94,153 @@ impl<R: Read> NbtFileReader<R> {
 
         self.reader
             .read_exact(&mut self.buf[..len])
-            .expect("failed to read");
-        &self.buf[..len]
+            .map_err(|e| CorruptNBTError::new_err(format!("could not parse nbt: {e}")))?;
+        Ok(&self.buf[..len])
     }
 
     fn read_end(&mut self) -> u8 {
         0
     }
 
-    fn read_byte(&mut self) -> u8 {
-        self.read(1)[0]
+    fn read_byte(&mut self) -> PyResult<u8> {
+        Ok(self.read(1)?[0])
     }
 
-    fn read_short(&mut self) -> i16 {
-        i16::from_be_bytes(self.read(2).try_into().unwrap())
+    fn read_short(&mut self) -> PyResult<i16> {
+        Ok(i16::from_be_bytes(self.read(2)?.try_into().unwrap()))
     }
 
-    fn read_int(&mut self) -> i32 {
-        i32::from_be_bytes(self.read(4).try_into().unwrap())
+    fn read_int(&mut self) -> PyResult<i32> {
+        Ok(i32::from_be_bytes(self.read(4)?.try_into().unwrap()))
     }
 
-    fn read_long(&mut self) -> i64 {
-        i64::from_be_bytes(self.read(8).try_into().unwrap())
+    fn read_long(&mut self) -> PyResult<i64> {
+        Ok(i64::from_be_bytes(self.read(8)?.try_into().unwrap()))
     }
 
-    fn read_float(&mut self) -> f32 {
-        f32::from_be_bytes(self.read(4).try_into().unwrap())
+    fn read_float(&mut self) -> PyResult<f32> {
+        Ok(f32::from_be_bytes(self.read(4)?.try_into().unwrap()))
     }
 
-    fn read_double(&mut self) -> f64 {
-        f64::from_be_bytes(self.read(8).try_into().unwrap())
+    fn read_double(&mut self) -> PyResult<f64> {
+        Ok(f64::from_be_bytes(self.read(8)?.try_into().unwrap()))
     }
 
-    fn read_byte_array<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyBytes> {
-        let len = u32::from_be_bytes(self.read(4).try_into().unwrap()) as usize;
-        let data = self.read(len);
-        PyBytes::new_bound(py, data)
+    fn read_byte_array<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let len = u32::from_be_bytes(self.read(4)?.try_into().unwrap()) as usize;
+        let data = self.read(len)?;
+        Ok(PyBytes::new_bound(py, data))
     }
 
-    fn read_int_array<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyTuple> {
-        let len = u32::from_be_bytes(self.read(4).try_into().unwrap()) as usize;
-        let data = self.read(len * 4);
+    fn read_int_array<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let len = u32::from_be_bytes(self.read(4)?.try_into().unwrap()) as usize;
+        let data = self.read(len * 4)?;
         let values = data
             .chunks_exact(4)
             .map(|d| i32::from_be_bytes(d.try_into().unwrap()));
 
-        PyTuple::new_bound(py, values)
+        Ok(PyTuple::new_bound(py, values))
     }
 
-    fn read_long_array<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyTuple> {
-        let len = u32::from_be_bytes(self.read(4).try_into().unwrap()) as usize;
-        let data = self.read(len * 8);
+    fn read_long_array<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let len = u32::from_be_bytes(self.read(4)?.try_into().unwrap()) as usize;
+        let data = self.read(len * 8)?;
         let values = data
             .chunks_exact(8)
             .map(|d| i64::from_be_bytes(d.try_into().unwrap()));
 
-        PyTuple::new_bound(py, values)
+        Ok(PyTuple::new_bound(py, values))
     }
 
-    fn read_string(&mut self) -> String {
-        let len = u16::from_be_bytes(self.read(2).try_into().unwrap()) as usize;
-        let data = self.read(len);
-        String::from_utf8_lossy(data).to_string()
+    fn read_string(&mut self) -> PyResult<String> {
+        let len = u16::from_be_bytes(self.read(2)?.try_into().unwrap()) as usize;
+        let data = self.read(len)?;
+        Ok(String::from_utf8_lossy(data).to_string())
     }
 
-    fn read_list<'py>(&mut self, py: Python<'py>) -> Vec<Py<PyAny>> {
-        let tag_id = self.read_byte();
-        let len = u32::from_be_bytes(self.read(4).try_into().unwrap()) as usize;
+    fn read_list<'py>(&mut self, py: Python<'py>) -> PyResult<Vec<Py<PyAny>>> {
+        let tag_id = self.read_byte()?;
+        let len = u32::from_be_bytes(self.read(4)?.try_into().unwrap()) as usize;
 
         let mut list = Vec::with_capacity(len);
         for _ in 0..len {
             let value = match tag_id {
                 0 => self.read_end().to_object(py),
-                1 => self.read_byte().to_object(py),
-                2 => self.read_short().to_object(py),
-                3 => self.read_int().to_object(py),
-                4 => self.read_long().to_object(py),
-                5 => self.read_float().to_object(py),
-                6 => self.read_double().to_object(py),
-                7 => self.read_byte_array(py).to_object(py),
-                8 => self.read_string().to_object(py),
-                9 => self.read_list(py).to_object(py),
-                10 => self.read_compound(py).to_object(py),
-                11 => self.read_int_array(py).to_object(py),
-                12 => self.read_long_array(py).to_object(py),
-                _ => panic!("Unexpected tag type"),
+                1 => self.read_byte()?.to_object(py),
+                2 => self.read_short()?.to_object(py),
+                3 => self.read_int()?.to_object(py),
+                4 => self.read_long()?.to_object(py),
+                5 => self.read_float()?.to_object(py),
+                6 => self.read_double()?.to_object(py),
+                7 => self.read_byte_array(py)?.to_object(py),
+                8 => self.read_string()?.to_object(py),
+                9 => self.read_list(py)?.to_object(py),
+                10 => self.read_compound(py)?.to_object(py),
+                11 => self.read_int_array(py)?.to_object(py),
+                12 => self.read_long_array(py)?.to_object(py),
+                _ => {
+                    return Err(CorruptNBTError::new_err(format!(
+                        "unexpected tag type: {tag_id}"
+                    )))
+                }
             };
 
             list.push(value);
         }
 
-        list
+        Ok(list)
     }
 
-    fn read_compound<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyDict> {
+    fn read_compound<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
         let tags = PyDict::new_bound(py);
 
         loop {
-            let tag_type = self.read(1)[0];
+            let tag_type = self.read(1)?[0];
             if tag_type == 0 {
                 break;
             }
 
-            let name = self.read_string();
+            let name = self.read_string()?;
 
             let payload = match tag_type {
                 0 => self.read_end().to_object(py),
-                1 => self.read_byte().to_object(py),
-                2 => self.read_short().to_object(py),
-                3 => self.read_int().to_object(py),
-                4 => self.read_long().to_object(py),
-                5 => self.read_float().to_object(py),
-                6 => self.read_double().to_object(py),
-                7 => self.read_byte_array(py).to_object(py),
-                8 => self.read_string().to_object(py),
-                9 => self.read_list(py).to_object(py),
-                10 => self.read_compound(py).to_object(py),
-                11 => self.read_int_array(py).to_object(py),
-                12 => self.read_long_array(py).to_object(py),
-                _ => panic!("Unexpected tag type"),
+                1 => self.read_byte()?.to_object(py),
+                2 => self.read_short()?.to_object(py),
+                3 => self.read_int()?.to_object(py),
+                4 => self.read_long()?.to_object(py),
+                5 => self.read_float()?.to_object(py),
+                6 => self.read_double()?.to_object(py),
+                7 => self.read_byte_array(py)?.to_object(py),
+                8 => self.read_string()?.to_object(py),
+                9 => self.read_list(py)?.to_object(py),
+                10 => self.read_compound(py)?.to_object(py),
+                11 => self.read_int_array(py)?.to_object(py),
+                12 => self.read_long_array(py)?.to_object(py),
+                _ => {
+                    return Err(CorruptNBTError::new_err(format!(
+                        "unexpected tag type: {tag_type}"
+                    )))
+                }
             };
 
-            tags.set_item(name, payload).expect("Failed to add to dict");
+            tags.set_item(name, payload)?;
         }
 
-        tags
+        Ok(tags)
     }
 
     /// Reads the entire file and returns (name, payload)
     /// name is the name of the root tag, and payload is a dictionary mapping
     /// names to their payloads
-    pub fn read_all<'py>(&mut self, py: Python<'py>) -> (String, Bound<'py, PyDict>) {
-        let tag_type = self.read(1)[0];
+    pub fn read_all<'py>(&mut self, py: Python<'py>) -> PyResult<(String, Bound<'py, PyDict>)> {
+        let tag_type = self.read(1)?[0];
         if tag_type != 10 {
-            panic!("Expected a tag compound");
+            return Err(CorruptNBTError::new_err("expected a tag compound"));
         }
 
-        let name = self.read_string();
-        let payload = self.read_compound(py);
-        return (name, payload);
-        //         except (struct.error, ValueError, TypeError, EOFError) as e:
-        //             raise CorruptNBTError("could not parse nbt: %s" % (str(e),))
+        let name = self.read_string()?;
+        let payload = self.read_compound(py)?;
+        Ok((name, payload))
     }
 }
 
@@ -235,6 +249,66 @@ enum RegionData {
     Loaded(Vec<u8>),
 }
 
+/// The compression codec a chunk's payload is stored with, as signalled by
+/// the byte right after its 4-byte length prefix.
+enum ChunkCompression {
+    /// Not used by the official client, but trivial to support here so...
+    Gzip,
+    /// The format everyone actually uses: a pure zlib stream.
+    Zlib,
+    /// Raw NBT with no framing at all.
+    Uncompressed,
+    /// An LZ4 frame, as produced by servers that opt into it for speed.
+    Lz4,
+}
+
+impl ChunkCompression {
+    fn from_id(id: u8) -> PyResult<Self> {
+        match id {
+            1 => Ok(Self::Gzip),
+            2 => Ok(Self::Zlib),
+            3 => Ok(Self::Uncompressed),
+            4 => Ok(Self::Lz4),
+            _ => Err(CorruptRegionError::new_err(format!(
+                "unsupported chunk compression type: {id} (should be 1, 2, 3 or 4)"
+            ))),
+        }
+    }
+}
+
+/// Wrap `data` in the decoder for `compression` and parse it as NBT.
+fn decode_chunk<'py, R: std::io::BufRead>(
+    py: Python<'py>,
+    compression: ChunkCompression,
+    data: R,
+) -> PyResult<(String, Bound<'py, PyDict>)> {
+    match compression {
+        ChunkCompression::Gzip => NbtFileReader::open(GzDecoder::new(data)).read_all(py),
+        ChunkCompression::Zlib => NbtFileReader::open(ZlibDecoder::new(data)).read_all(py),
+        ChunkCompression::Uncompressed => NbtFileReader::open(data).read_all(py),
+        ChunkCompression::Lz4 => NbtFileReader::open(FrameDecoder::new(data)).read_all(py),
+    }
+}
+
+/// Parse a region's `(x, z)` region coordinates from its `r.<x>.<z>.mca` file
+/// name, so external chunk payloads can be found by their global coordinates
+/// (the region's chunk-coordinate origin is 32 times these).
+/// Falls back to `(0, 0)` if the name doesn't match that pattern.
+fn parse_region_origin(path: &Path) -> (i32, i32) {
+    let origin = (|| {
+        let name = path.file_name()?.to_str()?;
+        let mut parts = name.split('.');
+        if parts.next()? != "r" {
+            return None;
+        }
+        let x = parts.next()?.parse().ok()?;
+        let z = parts.next()?.parse().ok()?;
+        Some((x, z))
+    })();
+
+    origin.unwrap_or((0, 0))
+}
+
 impl RegionData {
     fn load_data(&mut self) -> &[u8] {
         if let Self::NotLoaded(reader) = self {
@@ -257,6 +331,11 @@ impl RegionData {
 /// http://www.minecraftwiki.net/wiki/Beta_Level_Format
 #[pyclass]
 pub struct McrFileReader {
+    path: PathBuf,
+    /// This region's region coordinates, parsed from its file name; its
+    /// chunk-coordinate origin is 32 times these.
+    region_x: i32,
+    region_z: i32,
     region_data: RegionData,
     locations: [u32; 1024],
     timestamps: [i32; 1024],
@@ -289,7 +368,12 @@ impl McrFileReader {
             *ts = i32::from_be_bytes(ts_bytes.try_into().unwrap());
         }
 
+        let (region_x, region_z) = parse_region_origin(&path);
+
         Self {
+            path,
+            region_x,
+            region_z,
             region_data: RegionData::NotLoaded(reader),
             locations,
             timestamps,
@@ -332,50 +416,650 @@ impl McrFileReader {
         py: Python<'py>,
         x: i32,
         z: i32,
-    ) -> Option<(String, Bound<'py, PyDict>)> {
+    ) -> PyResult<Option<(String, Bound<'py, PyDict>)>> {
         let location = self.locations[(x.rem_euclid(32) + z.rem_euclid(32) * 32) as usize];
         let offset = (location >> 8) * 4096;
 
         if offset == 0 {
-            return None;
+            return Ok(None);
+        }
+
+        let rel_offset = offset as usize - 8192; // We already read the header
+        self.read_chunk_payload(py, rel_offset, x, z).map(Some)
+    }
+
+    /// Scan the region for structural corruption and fix what can be fixed.
+    ///
+    /// Every non-empty `locations` entry is checked for: an offset pointing
+    /// outside the file, a sector range overlapping another chunk's sectors,
+    /// a declared payload length that overruns its sectors, and a decompressed
+    /// `xPos`/`zPos` that doesn't match the chunk's slot. Chunks that only
+    /// overlap another chunk's sectors are salvageable and get shifted into
+    /// the first large-enough gap of free sectors instead of being dropped.
+    ///
+    /// When `delete_corrupt` is true, genuinely corrupt entries are zeroed
+    /// out of the header, overlapping chunks are relocated to free sectors,
+    /// and the rest of the file is left untouched. When false, the region is
+    /// fully defragmented: every chunk that can be salvaged is repacked
+    /// contiguously starting at sector 2, and only chunks with no usable
+    /// data left are dropped.
+    pub fn repair(&mut self, py: Python<'_>, delete_corrupt: bool) -> PyResult<RepairReport> {
+        let file_sectors =
+            HEADER_SECTORS + (self.region_data.load_data().len() / SECTOR_BYTES) as u32;
+        let slots = self.scan_slots(py, file_sectors);
+
+        if delete_corrupt {
+            self.repair_in_place(&slots, file_sectors)
+        } else {
+            self.repair_by_repacking(&slots)
+        }
+    }
+}
+
+/// What [`McrFileReader::repair`] found at one header slot.
+enum SlotStatus {
+    Empty,
+    /// No structural or content problems.
+    Valid,
+    /// Overlaps another chunk's sectors, but is otherwise decodable.
+    Overlapping,
+    /// Out-of-bounds offset, truncated payload, or mismatched `xPos`/`zPos`.
+    Corrupt,
+}
+
+struct Slot {
+    x: i32,
+    z: i32,
+    sector_start: u32,
+    sector_count: u32,
+    status: SlotStatus,
+}
+
+/// Find the first gap of at least `needed` free sectors, starting after the
+/// header and bounded by `file_sectors`, given the currently occupied ranges.
+/// Returns `None` if no gap inside the file is large enough; the caller is
+/// expected to append past `file_sectors` in that case.
+fn find_free_gap(occupied: &[(u32, u32)], needed: u32, file_sectors: u32) -> Option<u32> {
+    let mut sorted = occupied.to_vec();
+    sorted.sort_unstable();
+
+    let mut cursor = HEADER_SECTORS;
+    for (start, end) in sorted {
+        if start.saturating_sub(cursor) >= needed {
+            return Some(cursor);
         }
+        cursor = cursor.max(end);
+    }
+
+    (file_sectors.saturating_sub(cursor) >= needed).then_some(cursor)
+}
+
+/// Given each non-empty slot's `(index, sector_start, sector_end)`, return the
+/// indices whose range overlaps an earlier-starting range, tracking the
+/// furthest end seen so far (same running-max idea as [`find_free_gap`])
+/// rather than comparing only adjacent pairs in sorted order. This way a
+/// range nested inside an earlier, wider one is still caught even if a third
+/// range sorts between them. Only the later-sorted slot of an overlapping
+/// pair is reported; the earlier one is left in place.
+fn detect_overlaps(mut ranges: Vec<(usize, u32, u32)>) -> Vec<usize> {
+    ranges.sort_by_key(|&(_, start, _)| start);
+
+    let mut max_end: u32 = 0;
+    let mut overlapping = Vec::new();
+    for (idx, start, end) in ranges {
+        if start < max_end {
+            overlapping.push(idx);
+        }
+        max_end = max_end.max(end);
+    }
+    overlapping
+}
+
+#[cfg(test)]
+mod overlap_tests {
+    use super::detect_overlaps;
 
-        let data_offset = offset as usize - 8192; // We already read the header
+    #[test]
+    fn no_ranges_overlap() {
+        let ranges = vec![(0, 2, 6), (1, 6, 10), (2, 10, 12)];
+        assert!(detect_overlaps(ranges).is_empty());
+    }
+
+    #[test]
+    fn adjacent_pair_overlaps() {
+        let ranges = vec![(0, 2, 8), (1, 6, 10)];
+        assert_eq!(detect_overlaps(ranges), vec![1]);
+    }
+
+    #[test]
+    fn nested_range_separated_by_a_third_is_still_caught() {
+        // A = [2, 22), B = [5, 6), C = [10, 11): C is nested inside A but
+        // sorts after B, so a pairwise-adjacent check would only compare C
+        // against B and miss the real overlap with A.
+        let ranges = vec![(0, 2, 22), (1, 5, 6), (2, 10, 11)];
+        assert_eq!(detect_overlaps(ranges), vec![1, 2]);
+    }
+
+    #[test]
+    fn only_the_later_slot_of_a_pair_is_reported() {
+        let ranges = vec![(0, 2, 100), (1, 3, 4)];
+        let overlapping = detect_overlaps(ranges);
+        assert_eq!(overlapping, vec![1]);
+        assert!(!overlapping.contains(&0));
+    }
+}
+
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct RepairReport {
+    deleted: Vec<(i32, i32)>,
+    moved: Vec<(i32, i32)>,
+}
 
+#[pymethods]
+impl RepairReport {
+    /// Chunks that had no usable data left and were dropped from the header.
+    pub fn deleted(&self) -> Vec<(i32, i32)> {
+        self.deleted.clone()
+    }
+
+    /// Chunks that were relocated: shifted into free sectors to resolve a
+    /// sector overlap, or, when repacking, moved to a new sector by the
+    /// compaction.
+    pub fn moved(&self) -> Vec<(i32, i32)> {
+        self.moved.clone()
+    }
+}
+
+impl McrFileReader {
+    /// Read and decode the chunk payload at `rel_offset` (relative to the end
+    /// of the 8 KiB header). `x`/`z` may be local or global coordinates, as
+    /// with [`McrFileReader::load_chunk`]; they're only used to build the
+    /// `.mcc` sidecar name for externally-stored chunks, via this region's
+    /// global origin.
+    fn read_chunk_payload<'py>(
+        &mut self,
+        py: Python<'py>,
+        rel_offset: usize,
+        x: i32,
+        z: i32,
+    ) -> PyResult<(String, Bound<'py, PyDict>)> {
         let region_data = self.region_data.load_data();
-        let data_len = u32::from_be_bytes(
-            region_data[data_offset..data_offset + 4]
-                .try_into()
-                .unwrap(),
-        ) as usize;
-        let compression = region_data[data_offset + 4];
-        let gzip = match compression {
-            // gzip -- not used by the official client, but trivial to
-            // support here so...
-            1 => true,
-            // deflate -- pure zlib stream
-            2 => false,
-            //             # unsupported!
-            _ => panic!("Unsupported compression type"),
-            //             raise CorruptRegionError("unsupported chunk compression type: %i "
-            //                                      "(should be 1 or 2)" % (compression,))
-        };
+        if rel_offset + 5 > region_data.len() {
+            return Err(CorruptChunkError::new_err("chunk header is invalid"));
+        }
+
+        let data_len =
+            u32::from_be_bytes(region_data[rel_offset..rel_offset + 4].try_into().unwrap())
+                as usize;
+        if data_len < 1 || rel_offset + 4 + data_len > region_data.len() {
+            return Err(CorruptChunkError::new_err("chunk length is invalid"));
+        }
 
-        // Len includes compression byte
-        let chunk_data = Cursor::new(&region_data[data_offset + 5..data_offset + 5 + data_len - 1]);
-        //         except OSError as e:
-        //             raise CorruptChunkError("An OSError occurred: {}".format(e.strerror))
-        //         if len(header) != 5:
-        //             raise CorruptChunkError("chunk header is invalid")
+        let compression_byte = region_data[rel_offset + 4];
+        // Bit 0x80 marks a chunk too large to fit inline; its payload lives
+        // in a sidecar "c.<x>.<z>.mcc" file next to the region instead.
+        let external = compression_byte & 0x80 != 0;
+        let compression = ChunkCompression::from_id(compression_byte & 0x7F)?;
 
-        if gzip {
-            Some(NbtFileReader::open(GzDecoder::new(chunk_data)).read_all(py))
+        if external {
+            let global_x = self.region_x * 32 + x.rem_euclid(32);
+            let global_z = self.region_z * 32 + z.rem_euclid(32);
+            let mcc_path = self
+                .path
+                .with_file_name(format!("c.{global_x}.{global_z}.mcc"));
+            let data = std::fs::read(&mcc_path).map_err(|e| {
+                CorruptChunkError::new_err(format!("could not read {}: {}", mcc_path.display(), e))
+            })?;
+            decode_chunk(py, compression, Cursor::new(data))
         } else {
-            Some(NbtFileReader::open(ZlibDecoder::new(chunk_data)).read_all(py))
+            // Len includes compression byte
+            let chunk_data =
+                Cursor::new(&region_data[rel_offset + 5..rel_offset + 5 + data_len - 1]);
+            decode_chunk(py, compression, chunk_data)
+        }
+    }
+
+    /// Classify every non-empty `locations` entry.
+    fn scan_slots(&mut self, py: Python<'_>, file_sectors: u32) -> Vec<Slot> {
+        let mut slots = Vec::with_capacity(1024);
+
+        for (idx, &location) in self.locations.iter().enumerate() {
+            let sector_start = location >> 8;
+            let sector_count = location & 0xFF;
+            let x = (idx % 32) as i32;
+            let z = (idx / 32) as i32;
+
+            if sector_start == 0 && sector_count == 0 {
+                slots.push(Slot {
+                    x,
+                    z,
+                    sector_start,
+                    sector_count,
+                    status: SlotStatus::Empty,
+                });
+                continue;
+            }
+
+            let status = if sector_count == 0
+                || sector_start < HEADER_SECTORS
+                || sector_start + sector_count > file_sectors
+            {
+                SlotStatus::Corrupt
+            } else {
+                self.check_chunk(py, sector_start, sector_count, x, z)
+            };
+
+            slots.push(Slot {
+                x,
+                z,
+                sector_start,
+                sector_count,
+                status,
+            });
+        }
+
+        // Overlap is a relationship between two slots, so it can only be
+        // determined once every slot's (possibly corrupt) sector range is known.
+        let ranges: Vec<(usize, u32, u32)> = slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !matches!(s.status, SlotStatus::Empty))
+            .map(|(i, s)| (i, s.sector_start, s.sector_start + s.sector_count))
+            .collect();
+        for idx in detect_overlaps(ranges) {
+            if matches!(slots[idx].status, SlotStatus::Valid) {
+                slots[idx].status = SlotStatus::Overlapping;
+            }
+        }
+
+        slots
+    }
+
+    /// Check a structurally-plausible chunk's payload length and `xPos`/`zPos`.
+    fn check_chunk(
+        &mut self,
+        py: Python<'_>,
+        sector_start: u32,
+        sector_count: u32,
+        x: i32,
+        z: i32,
+    ) -> SlotStatus {
+        let sector_len = sector_count as usize * SECTOR_BYTES;
+        {
+            let region_data = self.region_data.load_data();
+            let rel_offset = (sector_start - HEADER_SECTORS) as usize * SECTOR_BYTES;
+            if rel_offset + 5 > region_data.len() {
+                return SlotStatus::Corrupt;
+            }
+            let data_len =
+                u32::from_be_bytes(region_data[rel_offset..rel_offset + 4].try_into().unwrap())
+                    as usize;
+            if data_len < 1 || 4 + data_len > sector_len {
+                return SlotStatus::Corrupt;
+            }
+        }
+
+        let rel_offset = (sector_start - HEADER_SECTORS) as usize * SECTOR_BYTES;
+        let (_, tags) = match self.read_chunk_payload(py, rel_offset, x, z) {
+            Ok(parsed) => parsed,
+            Err(_) => return SlotStatus::Corrupt,
+        };
+        let level = match tags.get_item("Level") {
+            Ok(Some(level)) => level,
+            _ => tags.into_any(),
+        };
+
+        let pos = level
+            .get_item("xPos")
+            .ok()
+            .and_then(|v| v.extract::<i32>().ok())
+            .zip(
+                level
+                    .get_item("zPos")
+                    .ok()
+                    .and_then(|v| v.extract::<i32>().ok()),
+            );
+
+        match pos {
+            Some((nbt_x, nbt_z)) if nbt_x.rem_euclid(32) == x && nbt_z.rem_euclid(32) == z => {
+                SlotStatus::Valid
+            }
+            _ => SlotStatus::Corrupt,
+        }
+    }
+
+    /// Zero out corrupt entries and shift overlapping ones into free sectors,
+    /// leaving the rest of the file untouched.
+    fn repair_in_place(&mut self, slots: &[Slot], file_sectors: u32) -> PyResult<RepairReport> {
+        let mut report = RepairReport::default();
+        // Read every relocated payload from this untouched snapshot, never
+        // from `new_data`, the same way `repair_by_repacking` reads from its
+        // own `old_data`. Otherwise relocating one overlapping chunk into a
+        // gap that happens to be another still-unprocessed overlapping
+        // chunk's *original* sectors would silently clobber that chunk's
+        // payload before its own turn comes around to read it.
+        let old_data = self.region_data.load_data().to_vec();
+        let mut new_data = old_data.clone();
+
+        // Sectors held by chunks that are staying put, so relocated chunks
+        // don't get shifted on top of them.
+        let mut occupied: Vec<(u32, u32)> = slots
+            .iter()
+            .filter(|s| matches!(s.status, SlotStatus::Valid))
+            .map(|s| (s.sector_start, s.sector_start + s.sector_count))
+            .collect();
+        let mut next_free_sector = file_sectors;
+        let mut relocations = Vec::new();
+
+        for slot in slots {
+            let idx = (slot.x + slot.z * 32) as usize;
+            match slot.status {
+                SlotStatus::Corrupt => {
+                    self.locations[idx] = 0;
+                    self.timestamps[idx] = 0;
+                    report.deleted.push((slot.x, slot.z));
+                }
+                SlotStatus::Overlapping => {
+                    let rel_offset = (slot.sector_start - HEADER_SECTORS) as usize * SECTOR_BYTES;
+                    let len = slot.sector_count as usize * SECTOR_BYTES;
+                    let payload = old_data[rel_offset..rel_offset + len].to_vec();
+
+                    let new_start = find_free_gap(&occupied, slot.sector_count, file_sectors)
+                        .unwrap_or_else(|| {
+                            let start = next_free_sector;
+                            next_free_sector += slot.sector_count;
+                            start
+                        });
+                    occupied.push((new_start, new_start + slot.sector_count));
+
+                    let new_rel_offset = (new_start - HEADER_SECTORS) as usize * SECTOR_BYTES;
+                    if new_rel_offset + len > new_data.len() {
+                        new_data.resize(new_rel_offset + len, 0);
+                    }
+                    new_data[new_rel_offset..new_rel_offset + len].copy_from_slice(&payload);
+
+                    self.locations[idx] = (new_start << 8) | slot.sector_count;
+                    relocations.push((new_start, payload));
+                    report.moved.push((slot.x, slot.z));
+                }
+                SlotStatus::Empty | SlotStatus::Valid => {}
+            }
+        }
+
+        self.region_data = RegionData::Loaded(new_data);
+        // Write the relocated payloads first so a failure partway through
+        // never leaves the on-disk header pointing at sectors that were
+        // never written; the header, which is what makes the move visible,
+        // is only rewritten once every payload has safely landed.
+        for (sector_start, payload) in relocations {
+            self.write_sectors(sector_start, &payload)?;
+        }
+        self.write_header()?;
+        Ok(report)
+    }
+
+    /// Repack every salvageable chunk contiguously starting at sector 2.
+    fn repair_by_repacking(&mut self, slots: &[Slot]) -> PyResult<RepairReport> {
+        let mut report = RepairReport::default();
+        let old_data = self.region_data.load_data().to_vec();
+        let mut new_data = Vec::with_capacity(old_data.len());
+        let mut new_locations = [0u32; 1024];
+        let mut cursor = HEADER_SECTORS;
+
+        for slot in slots {
+            let idx = (slot.x + slot.z * 32) as usize;
+            match slot.status {
+                SlotStatus::Empty => {}
+                SlotStatus::Corrupt => {
+                    report.deleted.push((slot.x, slot.z));
+                }
+                SlotStatus::Valid | SlotStatus::Overlapping => {
+                    let rel_offset = (slot.sector_start - HEADER_SECTORS) as usize * SECTOR_BYTES;
+                    let sector_len = slot.sector_count as usize * SECTOR_BYTES;
+                    new_data.extend_from_slice(&old_data[rel_offset..rel_offset + sector_len]);
+                    new_locations[idx] = (cursor << 8) | slot.sector_count;
+
+                    if cursor != slot.sector_start {
+                        report.moved.push((slot.x, slot.z));
+                    }
+                    cursor += slot.sector_count;
+                }
+            }
+        }
+
+        self.locations = new_locations;
+        self.region_data = RegionData::Loaded(new_data);
+        self.write_full()?;
+        Ok(report)
+    }
+
+    /// Open this region file for an in-place repair write, mapping any I/O
+    /// failure to a recoverable [`CorruptRegionError`] instead of aborting.
+    fn open_for_repair_write(&self) -> PyResult<File> {
+        OpenOptions::new().write(true).open(&self.path).map_err(|e| {
+            CorruptRegionError::new_err(format!(
+                "could not open {} for repair: {e}",
+                self.path.display()
+            ))
+        })
+    }
+
+    /// Map an I/O failure partway through a repair write to a recoverable
+    /// [`CorruptRegionError`].
+    fn repair_io_err(&self, e: std::io::Error) -> pyo3::PyErr {
+        CorruptRegionError::new_err(format!(
+            "failed to write repaired {}: {e}",
+            self.path.display()
+        ))
+    }
+
+    /// Rewrite just the 8 KiB `locations`/`timestamps` header in place.
+    fn write_header(&mut self) -> PyResult<()> {
+        let mut file = self.open_for_repair_write()?;
+
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| {
+                for loc in self.locations {
+                    file.write_all(&loc.to_be_bytes())?;
+                }
+                for ts in self.timestamps {
+                    file.write_all(&ts.to_be_bytes())?;
+                }
+                file.sync_all()
+            })
+            .map_err(|e| self.repair_io_err(e))
+    }
+
+    /// Write `payload` (a whole number of sectors) at `sector_start`,
+    /// extending the file if that sector lies past its current end.
+    fn write_sectors(&mut self, sector_start: u32, payload: &[u8]) -> PyResult<()> {
+        let mut file = self.open_for_repair_write()?;
+
+        file.seek(SeekFrom::Start(sector_start as u64 * SECTOR_BYTES as u64))
+            .and_then(|_| file.write_all(payload))
+            .and_then(|_| file.sync_all())
+            .map_err(|e| self.repair_io_err(e))
+    }
+
+    /// Rewrite the entire chunk data region first, truncating away any
+    /// sectors freed up by the repack, then the header last, so a failure
+    /// partway through never leaves the header pointing at data that was
+    /// never (fully) written.
+    fn write_full(&mut self) -> PyResult<()> {
+        let data = self.region_data.load_data().to_vec();
+        let mut file = self.open_for_repair_write()?;
+
+        file.seek(SeekFrom::Start(HEADER_SECTORS as u64 * SECTOR_BYTES as u64))
+            .and_then(|_| file.write_all(&data))
+            .and_then(|_| file.set_len(8192 + data.len() as u64))
+            .and_then(|_| file.sync_all())
+            .map_err(|e| self.repair_io_err(e))?;
+
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| {
+                for loc in self.locations {
+                    file.write_all(&loc.to_be_bytes())?;
+                }
+                for ts in self.timestamps {
+                    file.write_all(&ts.to_be_bytes())?;
+                }
+                file.sync_all()
+            })
+            .map_err(|e| self.repair_io_err(e))
+    }
+}
+
+/// Bits needed per palette index: `max(4, ceil(log2(palette_len)))`.
+fn palette_bits(palette_len: usize) -> u32 {
+    if palette_len <= 1 {
+        4
+    } else {
+        (usize::BITS - (palette_len - 1).leading_zeros()).max(4)
+    }
+}
+
+/// Number of `i64` longs needed to hold 4096 `bits`-wide indices.
+fn required_longs(bits: u32, modern_layout: bool) -> usize {
+    if modern_layout {
+        let per_long = (64 / bits) as usize;
+        4096_usize.div_ceil(per_long)
+    } else {
+        (4096 * bits as usize).div_ceil(64)
+    }
+}
+
+/// Decode a section's packed `BlockStates`/`data` long array into the 4096
+/// palette indices it encodes, in YZX order.
+///
+/// Set `modern_layout` for the 1.16+ format, where each long packs
+/// `floor(64 / bits)` indices low-to-high and no index straddles a long
+/// boundary. Clear it for the pre-1.16 format, where indices are packed
+/// tightly back-to-back and may span two adjacent longs.
+fn unpack_palette_indices(
+    data: &[i64],
+    palette_len: usize,
+    modern_layout: bool,
+) -> PyResult<[u16; 4096]> {
+    let bits = palette_bits(palette_len);
+    let needed = required_longs(bits, modern_layout);
+    if data.len() < needed {
+        return Err(CorruptNBTError::new_err(format!(
+            "block state data is too short: got {} longs, need at least {needed} for {palette_len} palette entries",
+            data.len()
+        )));
+    }
+
+    let mask: u64 = (1u64 << bits) - 1;
+    let mut indices = [0u16; 4096];
+
+    if modern_layout {
+        let per_long = (64 / bits) as usize;
+        for (i, index) in indices.iter_mut().enumerate() {
+            let long = data[i / per_long] as u64;
+            let shift = (i % per_long) as u32 * bits;
+            *index = ((long >> shift) & mask) as u16;
+        }
+    } else {
+        let mut bit_pos: u64 = 0;
+        for index in indices.iter_mut() {
+            let long_idx = (bit_pos / 64) as usize;
+            let bit_offset = (bit_pos % 64) as u32;
+            let low = data[long_idx] as u64 >> bit_offset;
+
+            *index = if bit_offset + bits <= 64 {
+                (low & mask) as u16
+            } else {
+                let high_bits = bit_offset + bits - 64;
+                let high = (data[long_idx + 1] as u64) & ((1u64 << high_bits) - 1);
+                ((low | (high << (64 - bit_offset))) & mask) as u16
+            };
+
+            bit_pos += bits as u64;
         }
-        //         except CorruptionError:
-        //             raise
-        //         except Exception as e:
-        //             raise CorruptChunkError("Misc error parsing chunk: " + str(e))
+    }
+
+    Ok(indices)
+}
+
+/// Serialize a 16x16x16 `u16` index grid as a NumPy `.npy` v1.0 buffer, so
+/// callers can `numpy.load` it with zero copies.
+fn write_npy_indices<'py>(py: Python<'py>, indices: &[u16; 4096]) -> Bound<'py, PyBytes> {
+    const PREFIX_LEN: usize = 6 + 2 + 2; // magic + version + header length field
+
+    let mut header =
+        "{'descr': '<u2', 'fortran_order': False, 'shape': (16, 16, 16), }".to_string();
+    let pad = (64 - (PREFIX_LEN + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(pad));
+    header.push('\n');
+
+    let mut buf = Vec::with_capacity(PREFIX_LEN + header.len() + indices.len() * 2);
+    buf.extend_from_slice(b"\x93NUMPY");
+    buf.push(1); // format major version
+    buf.push(0); // format minor version
+    buf.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    buf.extend_from_slice(header.as_bytes());
+    for index in indices {
+        buf.extend_from_slice(&index.to_le_bytes());
+    }
+
+    PyBytes::new_bound(py, &buf)
+}
+
+/// Decode a chunk section's block-state palette into a 16x16x16 `.npy`
+/// buffer of palette indices, so the renderer can consume block grids
+/// directly instead of re-parsing Python tuples.
+#[pyfunction]
+pub fn unpack_section_blocks<'py>(
+    py: Python<'py>,
+    palette_len: usize,
+    data: Vec<i64>,
+    modern_layout: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let indices = unpack_palette_indices(&data, palette_len, modern_layout)?;
+    Ok(write_npy_indices(py, &indices))
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::{required_longs, unpack_palette_indices};
+
+    #[test]
+    fn modern_layout_small_palette() {
+        // palette_len = 5 -> bits = 4 (floored up to the 4-bit minimum),
+        // per_long = 16 indices packed low-to-high, none straddling a long.
+        let palette_len = 5;
+        let needed = required_longs(4, true);
+        let mut data = vec![0i64; needed];
+        data[0] = (3 | (10 << 4) | (5 << 8) | (0 << 12)) as i64;
+
+        let indices = unpack_palette_indices(&data, palette_len, true).unwrap();
+        assert_eq!(&indices[..4], &[3, 10, 5, 0]);
+    }
+
+    #[test]
+    fn legacy_layout_index_spans_two_longs() {
+        // palette_len = 20 -> bits = 5, which doesn't divide 64 evenly, so
+        // index 12 (bit_pos 60..65) straddles data[0] and data[1].
+        let palette_len = 20;
+        let bits = 5;
+        let needed = required_longs(bits, false);
+        let mut data = vec![0i64; needed];
+
+        // Value 27 (0b11011): low 4 bits (0b1011 = 11) land in data[0]'s top
+        // nibble (bits 60..64), the remaining high bit (1) in data[1]'s bit 0.
+        data[0] = (11u64 << 60) as i64;
+        data[1] = 1;
+
+        let indices = unpack_palette_indices(&data, palette_len, false).unwrap();
+        assert_eq!(indices[12], 27);
+    }
+
+    #[test]
+    fn truncated_data_is_a_corrupt_nbt_error() {
+        let needed = required_longs(4, true);
+        let data = vec![0i64; needed - 1];
+
+        assert!(unpack_palette_indices(&data, 5, true).is_err());
     }
 }
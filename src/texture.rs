@@ -83,9 +83,7 @@ pub fn transform_image_side<'py>(
 
 fn affine_transform(src: &RgbaImage, config: &AffineTransformConfig) -> RgbaImage {
     if config.scale {
-        /* Scaling */
-        unimplemented!()
-        //return ImagingScaleAffine(imOut, imIn, x0, y0, x1, y1, a, fill);
+        return affine_scale(src, config);
     }
 
     if config.fixed {
@@ -95,6 +93,40 @@ fn affine_transform(src: &RgbaImage, config: &AffineTransformConfig) -> RgbaImag
     affine_float(src, config)
 }
 
+/// Pure axis-aligned scale/crop, equivalent to PIL's `ImagingScaleAffine`.
+/// Per-axis source coordinates are precomputed once and nearest-sampled,
+/// since `matrix[1] == 0 && matrix[3] == 0` means x and y never mix.
+fn affine_scale(src: &RgbaImage, config: &AffineTransformConfig) -> RgbaImage {
+    let mut dest = RgbaImage::new(config.width, config.height);
+    let [m0, _, m2, _, m4, m5] = config.matrix;
+
+    let xin: Vec<i64> = (0..config.width)
+        .map(|x| (m0 * (x as f64 + 0.5) + m2).floor() as i64)
+        .collect();
+    let yin: Vec<i64> = (0..config.height)
+        .map(|y| (m4 * (y as f64 + 0.5) + m5).floor() as i64)
+        .collect();
+
+    for (out_y, row) in dest.rows_mut().enumerate() {
+        let srcy = yin[out_y];
+        if srcy < 0 || srcy as u32 >= src.height() {
+            continue;
+        }
+        let srcy = srcy as u32;
+
+        for (out_x, out) in row.enumerate() {
+            let srcx = xin[out_x];
+            if srcx < 0 || srcx as u32 >= src.width() {
+                continue;
+            }
+
+            *out = *src.get_pixel(srcx as u32, srcy);
+        }
+    }
+
+    dest
+}
+
 fn affine_fixed(src: &RgbaImage, config: &AffineTransformConfig) -> RgbaImage {
     let mut dest = RgbaImage::new(config.width, config.height);
     let [m0, m1, mut m2, m3, m4, mut m5] = config.fixed_matrix;